@@ -15,7 +15,6 @@ use crate::{
         all_buffer_stages,
         all_image_stages,
         FramebufferKey,
-        MAX_COLOR_TARGETS,
         RenderPassContext,
         RenderPassKey,
     },
@@ -41,10 +40,9 @@ use crate::{
 #[cfg(feature = "local")]
 use crate::{ComputePassId, RenderPassId};
 
-use arrayvec::ArrayVec;
 use back::Backend;
 use hal::{command::RawCommandBuffer, Device as _};
-use log::trace;
+use log::{trace, warn};
 
 use std::{collections::hash_map::Entry, iter, slice, thread::ThreadId};
 
@@ -53,12 +51,19 @@ use std::{collections::hash_map::Entry, iter, slice, thread::ThreadId};
 pub enum LoadOp {
     Clear = 0,
     Load = 1,
+    /// Contents are undefined at the start of the pass: tiled GPUs need neither
+    /// clear nor load the attachment. The `clear_values` builder emits no
+    /// `ClearValue` for it, just as for `Load`.
+    DontCare = 2,
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
 pub enum StoreOp {
     Store = 0,
+    /// Contents may be discarded at the end of the pass, letting tiled GPUs skip
+    /// writing the attachment back to memory.
+    Discard = 1,
 }
 
 #[repr(C)]
@@ -81,11 +86,82 @@ pub struct RenderPassDepthStencilAttachmentDescriptor<T> {
     pub clear_stencil: u32,
 }
 
+/// Sentinel subpass index identifying commands outside the render pass, used in
+/// `RenderPassDependencyDescriptor` (mirrors `VK_SUBPASS_EXTERNAL`).
+pub const SUBPASS_EXTERNAL: u32 = !0;
+
+/// Sentinel attachment index marking a color target that is not resolved, used in
+/// a subpass' resolve slice so that resolving and non-resolving color attachments
+/// can be mixed within one pass (mirrors `VK_ATTACHMENT_UNUSED`).
+pub const ATTACHMENT_UNUSED: u32 = !0;
+
+/// Description of a single subpass within a render pass.
+///
+/// The index arrays refer to entries in the render pass' combined attachment list,
+/// as produced by `command_encoder_begin_render_pass`: the color targets in order,
+/// then `depth_stencil_attachment` if present, then one resolve attachment for each
+/// color target with a non-null `resolve_target`. This follows the shape of
+/// vulkano's `SubpassDesc`.
+#[repr(C)]
+pub struct RenderPassSubpassDescriptor {
+    pub colors: *const u32,
+    pub colors_length: usize,
+    pub depth_stencil: *const u32,
+    pub inputs: *const u32,
+    pub inputs_length: usize,
+    pub resolves: *const u32,
+    pub resolves_length: usize,
+    pub preserves: *const u32,
+    pub preserves_length: usize,
+}
+
+/// Explicit execution/memory dependency between two subpasses. `src_subpass` and
+/// `dst_subpass` index into the subpass list, or are `SUBPASS_EXTERNAL`. The mask
+/// fields carry raw `hal::pso::PipelineStage`/`hal::image::Access` bits.
+#[repr(C)]
+pub struct RenderPassDependencyDescriptor {
+    pub src_subpass: u32,
+    pub dst_subpass: u32,
+    pub src_stages: u32,
+    pub dst_stages: u32,
+    pub src_access: u32,
+    pub dst_access: u32,
+}
+
+/// Owned, hashable description of a single subpass, stored in `RenderPassKey`/
+/// `RenderPassContext` so that two passes with identical attachments but
+/// different subpass graphs do not collide in the render-pass cache. Indices
+/// refer to entries in the render pass' combined attachment list.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SubpassKey {
+    pub colors: Vec<u32>,
+    pub depth_stencil: Option<u32>,
+    pub inputs: Vec<u32>,
+    pub resolves: Vec<u32>,
+    pub preserves: Vec<u32>,
+}
+
+/// Owned, hashable subpass dependency, stored alongside `SubpassKey` in the
+/// render-pass cache key. Mirrors `RenderPassDependencyDescriptor`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct SubpassDependencyKey {
+    pub src_subpass: u32,
+    pub dst_subpass: u32,
+    pub src_stages: u32,
+    pub dst_stages: u32,
+    pub src_access: u32,
+    pub dst_access: u32,
+}
+
 #[repr(C)]
 pub struct RenderPassDescriptor {
     pub color_attachments: *const RenderPassColorAttachmentDescriptor,
     pub color_attachments_length: usize,
     pub depth_stencil_attachment: *const RenderPassDepthStencilAttachmentDescriptor<TextureViewId>,
+    pub subpasses: *const RenderPassSubpassDescriptor,
+    pub subpasses_length: usize,
+    pub dependencies: *const RenderPassDependencyDescriptor,
+    pub dependencies_length: usize,
 }
 
 pub struct CommandBuffer<B: hal::Backend> {
@@ -187,6 +263,24 @@ pub fn command_encoder_begin_render_pass(
     let color_attachments =
         unsafe { slice::from_raw_parts(desc.color_attachments, desc.color_attachments_length) };
     let depth_stencil_attachment = unsafe { desc.depth_stencil_attachment.as_ref() };
+    let subpasses = if desc.subpasses.is_null() {
+        &[][..]
+    } else {
+        unsafe { slice::from_raw_parts(desc.subpasses, desc.subpasses_length) }
+    };
+    let dependencies = if desc.dependencies.is_null() {
+        &[][..]
+    } else {
+        unsafe { slice::from_raw_parts(desc.dependencies, desc.dependencies_length) }
+    };
+
+    // Resolve targets are handled independently per color attachment: some targets
+    // may resolve while others do not. Each color attachment with a non-null
+    // `resolve_target` contributes a resolve attachment to the render pass, and the
+    // subpass resolve slice uses `ATTACHMENT_UNUSED` for the targets that don't.
+    let has_resolves = color_attachments
+        .iter()
+        .any(|at| !at.resolve_target.is_null());
 
     let rp_key = {
         let trackers = &mut cmb.trackers;
@@ -262,9 +356,10 @@ pub fn command_encoder_begin_render_pass(
 
         let colors = color_keys.collect();
 
-        let resolve_keys = if !color_attachments[0].resolve_target.is_null() {
-            // TODO: how to handle invalid case where not all color targets have resolves
-            Some(color_attachments.iter().map(|at| {
+        let resolve_keys = if has_resolves {
+            // Only color targets that actually carry a resolve contribute a resolve
+            // attachment; the subpass resolve slice references the others as unused.
+            Some(color_attachments.iter().filter(|at| !at.resolve_target.is_null()).map(|at| {
                 let id = unsafe { *at.resolve_target.as_ref().unwrap() };
                 let view = &view_guard[id];
 
@@ -309,10 +404,78 @@ pub fn command_encoder_begin_render_pass(
             None
         };
 
+        // Capture the full subpass graph in the cache key so that two passes with
+        // identical attachments but different subpass/dependency layouts do not
+        // collide in `render_pass_cache`. When no explicit subpasses are supplied,
+        // synthesize the key for the default single subpass that this function
+        // builds below, so the implicit and explicit paths hash consistently.
+        let n = color_attachments.len() as u32;
+        let depth_index = n;
+        let resolve_index = if depth_stencil_attachment.is_some() { n + 1 } else { n };
+        let read_indices = |ptr: *const u32, len: usize| -> Vec<u32> {
+            if ptr.is_null() {
+                Vec::new()
+            } else {
+                unsafe { slice::from_raw_parts(ptr, len) }.to_vec()
+            }
+        };
+        let subpasses = if subpasses.is_empty() {
+            vec![SubpassKey {
+                colors: (0 .. n).collect(),
+                depth_stencil: depth_stencil_attachment.map(|_| depth_index),
+                inputs: Vec::new(),
+                resolves: if has_resolves {
+                    // One entry per color target, in order: resolving targets point at
+                    // their resolve attachment (laid out consecutively after the
+                    // colors and optional depth/stencil), the rest are `ATTACHMENT_UNUSED`.
+                    let mut next = resolve_index;
+                    color_attachments
+                        .iter()
+                        .map(|at| {
+                            if at.resolve_target.is_null() {
+                                ATTACHMENT_UNUSED
+                            } else {
+                                let index = next;
+                                next += 1;
+                                index
+                            }
+                        })
+                        .collect()
+                } else {
+                    Vec::new()
+                },
+                preserves: Vec::new(),
+            }]
+        } else {
+            subpasses
+                .iter()
+                .map(|sp| SubpassKey {
+                    colors: read_indices(sp.colors, sp.colors_length),
+                    depth_stencil: unsafe { sp.depth_stencil.as_ref() }.copied(),
+                    inputs: read_indices(sp.inputs, sp.inputs_length),
+                    resolves: read_indices(sp.resolves, sp.resolves_length),
+                    preserves: read_indices(sp.preserves, sp.preserves_length),
+                })
+                .collect()
+        };
+        let dependencies = dependencies
+            .iter()
+            .map(|dep| SubpassDependencyKey {
+                src_subpass: dep.src_subpass,
+                dst_subpass: dep.dst_subpass,
+                src_stages: dep.src_stages,
+                dst_stages: dep.dst_stages,
+                src_access: dep.src_access,
+                dst_access: dep.dst_access,
+            })
+            .collect();
+
         RenderPassKey {
             colors,
             depth_stencil,
             resolves: resolve_keys.map(|rk| rk.collect()),
+            subpasses,
+            dependencies,
         }
     };
 
@@ -320,37 +483,149 @@ pub fn command_encoder_begin_render_pass(
     let render_pass = match render_pass_cache.entry(rp_key.clone()) {
         Entry::Occupied(e) => e.into_mut(),
         Entry::Vacant(e) => {
-            let mut ids: ArrayVec<[_; 2 * MAX_COLOR_TARGETS + 1]> = ArrayVec::new();
-            for i in 0..color_attachments.len() {
-                ids.push((i, hal::image::Layout::ColorAttachmentOptimal));
-            }
-            let depth_id = ids.len();
-            if let Some(_) = depth_stencil_attachment {
-                ids.push((ids.len(), hal::image::Layout::DepthStencilAttachmentOptimal));
-            }
-            let resolve_start = ids.len();
-            for i in 0..color_attachments.len() {
-                ids.push((ids.len() + i, hal::image::Layout::ColorAttachmentOptimal));
-            }
-
-            let subpass = hal::pass::SubpassDesc {
-                colors: &ids[.. depth_id],
-                depth_stencil: depth_stencil_attachment.map(|_| &ids[depth_id]),
-                inputs: &[],
-                resolves: if !color_attachments[0].resolve_target.is_null() {
-                    &ids[resolve_start ..]
+            // The subpass graph and dependencies come straight from the caller over
+            // the C ABI, so every index and mask is untrusted. This function
+            // is reached from an `extern "C"` entry point — panicking here would
+            // unwind across the ABI — so out-of-range entries are dropped and unknown
+            // mask bits truncated with a warning, rather than asserted. The combined
+            // attachment list is `colors`, the optional depth/stencil attachment,
+            // then the resolve attachments.
+            let resolve_count = color_attachments
+                .iter()
+                .filter(|at| !at.resolve_target.is_null())
+                .count();
+            let total_attachments = (color_attachments.len()
+                + if depth_stencil_attachment.is_some() { 1 } else { 0 }
+                + resolve_count) as u32;
+            let valid_index = |i: u32| i == ATTACHMENT_UNUSED || i < total_attachments;
+
+            // Each subpass borrows slices of attachment references, so the owned
+            // backing storage has to outlive the `create_render_pass` call. Every
+            // entry is `(colors, depth_stencil, inputs, resolves, preserves)`, built
+            // from the subpass graph captured in the cache key above.
+            type AttachmentRef = (usize, hal::image::Layout);
+            let map_refs = |indices: &[u32], layout: hal::image::Layout| -> Vec<AttachmentRef> {
+                indices
+                    .iter()
+                    .filter_map(|&i| {
+                        if valid_index(i) {
+                            Some((i as usize, layout))
+                        } else {
+                            warn!(
+                                "ignoring out-of-range subpass attachment index {} (>= {})",
+                                i, total_attachments
+                            );
+                            None
+                        }
+                    })
+                    .collect()
+            };
+            let subpass_storage: Vec<(
+                Vec<AttachmentRef>,
+                Option<AttachmentRef>,
+                Vec<AttachmentRef>,
+                Vec<AttachmentRef>,
+                Vec<usize>,
+            )> = rp_key
+                .subpasses
+                .iter()
+                .map(|sp| {
+                    let colors = map_refs(&sp.colors, hal::image::Layout::ColorAttachmentOptimal);
+                    let inputs = map_refs(&sp.inputs, hal::image::Layout::ShaderReadOnlyOptimal);
+                    let resolves =
+                        map_refs(&sp.resolves, hal::image::Layout::ColorAttachmentOptimal);
+                    let preserves = sp
+                        .preserves
+                        .iter()
+                        .filter_map(|&i| {
+                            if i != ATTACHMENT_UNUSED && valid_index(i) {
+                                Some(i as usize)
+                            } else {
+                                warn!(
+                                    "ignoring out-of-range subpass preserve index {} (>= {})",
+                                    i, total_attachments
+                                );
+                                None
+                            }
+                        })
+                        .collect();
+                    let depth_stencil = sp.depth_stencil.and_then(|i| {
+                        if i != ATTACHMENT_UNUSED && valid_index(i) {
+                            Some((i as usize, hal::image::Layout::DepthStencilAttachmentOptimal))
+                        } else {
+                            warn!(
+                                "ignoring out-of-range subpass depth/stencil index {} (>= {})",
+                                i, total_attachments
+                            );
+                            None
+                        }
+                    });
+                    (colors, depth_stencil, inputs, resolves, preserves)
+                })
+                .collect();
+
+            let subpass_descs: Vec<hal::pass::SubpassDesc> = subpass_storage
+                .iter()
+                .map(|(colors, depth_stencil, inputs, resolves, preserves)| {
+                    hal::pass::SubpassDesc {
+                        colors,
+                        depth_stencil: depth_stencil.as_ref(),
+                        inputs,
+                        resolves,
+                        preserves,
+                    }
+                })
+                .collect();
+
+            let subpass_count = rp_key.subpasses.len();
+            // A dependency that names a subpass outside the graph is dropped, and
+            // mask bits the current hal flag set doesn't define are truncated away
+            // with a warning — both would otherwise panic on caller input.
+            let subpass_ref = |index: u32| -> Option<hal::pass::SubpassRef> {
+                if index == SUBPASS_EXTERNAL {
+                    Some(hal::pass::SubpassRef::External)
+                } else if (index as usize) < subpass_count {
+                    Some(hal::pass::SubpassRef::Pass(index as usize))
                 } else {
-                    &[]
-                },
-                preserves: &[],
+                    warn!(
+                        "ignoring subpass dependency referencing subpass {} of {}",
+                        index, subpass_count
+                    );
+                    None
+                }
             };
-
-            println!("{:?}", e.key());
+            let stage = |bits: u32| {
+                let flags = hal::pso::PipelineStage::from_bits_truncate(bits);
+                if flags.bits() != bits {
+                    warn!(
+                        "ignoring unknown pipeline-stage bits {:#x} in subpass dependency",
+                        bits & !flags.bits()
+                    );
+                }
+                flags
+            };
+            let access = |bits: u32| {
+                let flags = hal::image::Access::from_bits_truncate(bits);
+                if flags.bits() != bits {
+                    warn!(
+                        "ignoring unknown access bits {:#x} in subpass dependency",
+                        bits & !flags.bits()
+                    );
+                }
+                flags
+            };
+            let dependencies = dependencies.iter().filter_map(|dep| {
+                Some(hal::pass::SubpassDependency {
+                    passes: subpass_ref(dep.src_subpass)? .. subpass_ref(dep.dst_subpass)?,
+                    stages: stage(dep.src_stages) .. stage(dep.dst_stages),
+                    accesses: access(dep.src_access) .. access(dep.dst_access),
+                })
+            });
 
             let pass = unsafe {
                 device
                     .raw
-                    .create_render_pass(e.key().all(), &[subpass], &[])
+                    .create_render_pass(e.key().all(), &subpass_descs, dependencies)
             }
             .unwrap();
             e.insert(pass)
@@ -361,10 +636,11 @@ pub fn command_encoder_begin_render_pass(
     let fb_key = FramebufferKey {
         colors: color_attachments.iter().map(|at| at.attachment).collect(),
         depth_stencil: depth_stencil_attachment.map(|at| at.attachment),
-        resolves: if !color_attachments[0].resolve_target.is_null() {
+        resolves: if has_resolves {
             Some(
                 color_attachments
                     .iter()
+                    .filter(|at| !at.resolve_target.is_null())
                     .map(|at| unsafe { *at.resolve_target.as_ref().expect("Expected resolve target") })
                     .collect(),
             )
@@ -399,12 +675,15 @@ pub fn command_encoder_begin_render_pass(
         }
     };
 
+    // Clear values are emitted strictly in attachment order: colors, then the
+    // depth/stencil attachment. Resolve attachments contribute no clear values,
+    // although their load op is copied from the color target they resolve.
     let clear_values = color_attachments
         .iter()
         .zip(&rp_key.colors)
         .flat_map(|(at, key)| {
             match at.load_op {
-                LoadOp::Load => None,
+                LoadOp::Load | LoadOp::DontCare => None,
                 LoadOp::Clear => {
                     use hal::format::ChannelType;
                     //TODO: validate sign/unsign and normalized ranges of the color values
@@ -433,42 +712,13 @@ pub fn command_encoder_begin_render_pass(
         })
         .chain(depth_stencil_attachment.and_then(|at| {
             match (at.depth_load_op, at.stencil_load_op) {
-                (LoadOp::Load, LoadOp::Load) => None,
                 (LoadOp::Clear, _) | (_, LoadOp::Clear) => {
                     let value = hal::command::ClearDepthStencil(at.clear_depth, at.clear_stencil);
                     Some(hal::command::ClearValueRaw::from(
                         hal::command::ClearValue::DepthStencil(value),
                     ))
                 }
-            }
-        }))
-        .chain(color_attachments.iter().zip(&rp_key.colors).flat_map(|(at, key)| {
-            match at.load_op {
-                LoadOp::Load => None,
-                LoadOp::Clear => {
-                    use hal::format::ChannelType;
-                    //TODO: validate sign/unsign and normalized ranges of the color values
-                    let value = match key.format.unwrap().base_format().1 {
-                        ChannelType::Unorm
-                        | ChannelType::Snorm
-                        | ChannelType::Ufloat
-                        | ChannelType::Sfloat
-                        | ChannelType::Uscaled
-                        | ChannelType::Sscaled
-                        | ChannelType::Srgb => {
-                            hal::command::ClearColor::Float(conv::map_color_f32(&at.clear_color))
-                        }
-                        ChannelType::Sint => {
-                            hal::command::ClearColor::Int(conv::map_color_i32(&at.clear_color))
-                        }
-                        ChannelType::Uint => {
-                            hal::command::ClearColor::Uint(conv::map_color_u32(&at.clear_color))
-                        }
-                    };
-                    Some(hal::command::ClearValueRaw::from(
-                        hal::command::ClearValue::Color(value),
-                    ))
-                }
+                _ => None,
             }
         }));
 
@@ -496,16 +746,19 @@ pub fn command_encoder_begin_render_pass(
             .map(|at| view_guard[at.attachment].format)
             .collect(),
         depth_stencil: depth_stencil_attachment.map(|at| view_guard[at.attachment].format),
-        resolves: if !color_attachments[0].resolve_target.is_null() {
+        resolves: if has_resolves {
             Some(
                 color_attachments
                     .iter()
+                    .filter(|at| !at.resolve_target.is_null())
                     .map(|at| view_guard[unsafe { *at.resolve_target.as_ref().unwrap() }].format)
                     .collect(),
             )
         } else {
             None
         },
+        subpasses: rp_key.subpasses.clone(),
+        dependencies: rp_key.dependencies.clone(),
     };
 
     let index_state = IndexState {